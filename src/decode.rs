@@ -0,0 +1,608 @@
+extern crate serde;
+
+use std::cell::Cell;
+use std::char;
+use std::io;
+use std::str;
+
+use serde::de;
+
+use error::{Error, ErrorCode};
+
+thread_local! {
+    /// The position of the byte the `Decoder` is currently looking at.
+    ///
+    /// `de::Error`'s `syntax_error`/`end_of_stream_error`/`unknown_field_error`
+    /// constructors (and the `From<de::value::Error>` conversions in
+    /// `error.rs`) are associated functions with no access to the
+    /// `Decoder`, since that's how `serde::de::Error` is shaped. Mirroring
+    /// the position here lets them report where parsing actually was
+    /// instead of always claiming line 0, column 0.
+    static POSITION: Cell<(usize, usize)> = Cell::new((0, 0));
+}
+
+/// Returns the position the most recently active `Decoder` last advanced
+/// to. Used by `error::Error`'s `de::Error` constructors.
+pub fn current_position() -> (usize, usize) {
+    POSITION.with(|p| p.get())
+}
+
+/// A structure for implementing deserialization from RON.
+pub struct Decoder<Iter: Iterator<Item = io::Result<u8>>> {
+    rdr: Iter,
+    ch: Option<u8>,
+    line: usize,
+    col: usize,
+}
+
+impl<Iter> Decoder<Iter>
+    where Iter: Iterator<Item = io::Result<u8>>,
+{
+    /// Creates the RON parsing struct from an `Iterator` of bytes.
+    pub fn new(rdr: Iter) -> Decoder<Iter> {
+        let mut decoder = Decoder {
+            rdr: rdr,
+            ch: Some(b'\x00'),
+            line: 1,
+            col: 0,
+        };
+
+        decoder.bump();
+        decoder
+    }
+
+    fn eof(&self) -> bool {
+        self.ch.is_none()
+    }
+
+    fn ch_or_null(&self) -> u8 {
+        self.ch.unwrap_or(b'\x00')
+    }
+
+    fn bump(&mut self) {
+        self.ch = match self.rdr.next() {
+            Some(Ok(ch)) => Some(ch),
+            Some(Err(_)) => None,
+            None => None,
+        };
+
+        match self.ch {
+            Some(b'\n') => {
+                self.line += 1;
+                self.col = 0;
+            }
+            Some(_) => self.col += 1,
+            None => {}
+        }
+
+        POSITION.with(|p| p.set((self.line, self.col)));
+    }
+
+    fn error(&self, reason: ErrorCode) -> Error {
+        Error::Syntax(reason, self.line, self.col)
+    }
+
+    fn parse_whitespace(&mut self) {
+        while !self.eof() {
+            match self.ch_or_null() {
+                b' ' | b'\n' | b'\t' | b'\r' => self.bump(),
+                _ => break,
+            }
+        }
+    }
+
+    fn parse_ident(&mut self, ident: &[u8]) -> Result<(), Error> {
+        for &c in ident {
+            if Some(c) != self.ch {
+                return Err(self.error(ErrorCode::ExpectedSomeIdent));
+            }
+            self.bump();
+        }
+
+        Ok(())
+    }
+
+    /// Parses a bare identifier (a struct or enum variant name).
+    fn parse_name(&mut self) -> Result<String, Error> {
+        let mut name = Vec::new();
+
+        while !self.eof() {
+            match self.ch_or_null() {
+                c @ b'a'...b'z' | c @ b'A'...b'Z' | c @ b'0'...b'9' | c @ b'_' => {
+                    name.push(c);
+                    self.bump();
+                }
+                _ => break,
+            }
+        }
+
+        if name.is_empty() {
+            return Err(self.error(ErrorCode::ExpectedName));
+        }
+
+        String::from_utf8(name).map_err(|_| self.error(ErrorCode::NotUtf8))
+    }
+
+    /// `¶Name¶` is how a unit enum variant or a named unit struct is written
+    /// out by `Encoder::visit_enum_unit`.
+    fn parse_pilcrow_name(&mut self) -> Result<String, Error> {
+        try!(self.expect_pilcrow());
+        let name = try!(self.parse_name());
+        try!(self.expect_pilcrow());
+        Ok(name)
+    }
+
+    fn expect_pilcrow(&mut self) -> Result<(), Error> {
+        if self.ch_or_null() != 0xC2 {
+            return Err(self.error(ErrorCode::ExpectedName));
+        }
+        self.bump();
+
+        if self.ch_or_null() != 0xB6 {
+            return Err(self.error(ErrorCode::ExpectedName));
+        }
+        self.bump();
+
+        Ok(())
+    }
+
+    fn looking_at_pilcrow(&self) -> bool {
+        self.ch == Some(0xC2)
+    }
+
+    fn parse_number(&mut self) -> Result<Number, Error> {
+        let mut buf = Vec::new();
+        let mut is_float = false;
+
+        if self.ch_or_null() == b'-' {
+            buf.push(b'-');
+            self.bump();
+        }
+
+        match self.ch_or_null() {
+            b'0'...b'9' => {
+                while let b'0'...b'9' = self.ch_or_null() {
+                    buf.push(self.ch_or_null());
+                    self.bump();
+                }
+            }
+            _ => return Err(self.error(ErrorCode::InvalidNumber)),
+        }
+
+        if self.ch_or_null() == b'.' {
+            is_float = true;
+            buf.push(b'.');
+            self.bump();
+
+            match self.ch_or_null() {
+                b'0'...b'9' => {
+                    while let b'0'...b'9' = self.ch_or_null() {
+                        buf.push(self.ch_or_null());
+                        self.bump();
+                    }
+                }
+                _ => return Err(self.error(ErrorCode::InvalidNumber)),
+            }
+        }
+
+        if self.ch_or_null() == b'e' || self.ch_or_null() == b'E' {
+            is_float = true;
+            buf.push(self.ch_or_null());
+            self.bump();
+
+            if self.ch_or_null() == b'+' || self.ch_or_null() == b'-' {
+                buf.push(self.ch_or_null());
+                self.bump();
+            }
+
+            match self.ch_or_null() {
+                b'0'...b'9' => {
+                    while let b'0'...b'9' = self.ch_or_null() {
+                        buf.push(self.ch_or_null());
+                        self.bump();
+                    }
+                }
+                _ => return Err(self.error(ErrorCode::InvalidNumber)),
+            }
+        }
+
+        let s = try!(str::from_utf8(&buf).map_err(|_| self.error(ErrorCode::NotUtf8)));
+
+        if is_float {
+            s.parse().map(Number::F64).map_err(|_| self.error(ErrorCode::InvalidNumber))
+        } else if buf[0] == b'-' {
+            s.parse().map(Number::I64).map_err(|_| self.error(ErrorCode::InvalidNumber))
+        } else {
+            s.parse().map(Number::U64).map_err(|_| self.error(ErrorCode::InvalidNumber))
+        }
+    }
+
+    fn parse_escape(&mut self) -> Result<char, Error> {
+        self.bump();
+
+        let c = match self.ch_or_null() {
+            b'"' => '"',
+            b'\'' => '\'',
+            b'\\' => '\\',
+            b'/' => '/',
+            b'b' => '\x08',
+            b'f' => '\x0c',
+            b'n' => '\n',
+            b'r' => '\r',
+            b't' => '\t',
+            b'u' => return self.parse_unicode_escape(),
+            _ => return Err(self.error(ErrorCode::InvalidEscape)),
+        };
+
+        self.bump();
+        Ok(c)
+    }
+
+    fn parse_unicode_escape(&mut self) -> Result<char, Error> {
+        let mut n = 0u32;
+
+        for _ in 0..4 {
+            self.bump();
+
+            n = n << 4 | match self.ch_or_null() {
+                c @ b'0'...b'9' => (c - b'0') as u32,
+                c @ b'a'...b'f' => (c - b'a' + 10) as u32,
+                c @ b'A'...b'F' => (c - b'A' + 10) as u32,
+                _ => return Err(self.error(ErrorCode::UnrecognizedHex)),
+            };
+        }
+
+        self.bump();
+
+        if 0xD800 <= n && n <= 0xDBFF {
+            // A leading surrogate must be immediately followed by a
+            // trailing one; we don't support combining them yet.
+            return Err(self.error(ErrorCode::LoneLeadingSurrogateInHexEscape));
+        }
+
+        char::from_u32(n).ok_or_else(|| self.error(ErrorCode::InvalidUnicodeCodePoint))
+    }
+
+    fn parse_string(&mut self) -> Result<String, Error> {
+        self.bump();
+
+        let mut s = String::new();
+
+        loop {
+            if self.eof() {
+                return Err(self.error(ErrorCode::EOFWhileParsingString));
+            }
+
+            match self.ch_or_null() {
+                b'"' => {
+                    self.bump();
+                    return Ok(s);
+                }
+                b'\\' => s.push(try!(self.parse_escape())),
+                _ => s.push(try!(self.parse_utf8_char())),
+            }
+        }
+    }
+
+    fn parse_char(&mut self) -> Result<char, Error> {
+        self.bump();
+
+        let c = match self.ch_or_null() {
+            b'\\' => try!(self.parse_escape()),
+            _ => try!(self.parse_utf8_char()),
+        };
+
+        if self.ch_or_null() != b'\'' {
+            return Err(self.error(ErrorCode::InvalidEscape));
+        }
+        self.bump();
+
+        Ok(c)
+    }
+
+    /// Decodes the (possibly multi-byte) UTF-8 character starting at the
+    /// current byte and bumps past all of its bytes. A plain `byte as char`
+    /// cast would instead reinterpret each raw UTF-8 byte as its own
+    /// Latin-1 code point, corrupting any non-ASCII text.
+    fn parse_utf8_char(&mut self) -> Result<char, Error> {
+        let width = utf8_char_width(self.ch_or_null());
+        if width == 0 {
+            return Err(self.error(ErrorCode::NotUtf8));
+        }
+
+        let mut buf = [0u8; 4];
+        for slot in buf.iter_mut().take(width) {
+            *slot = self.ch_or_null();
+            self.bump();
+        }
+
+        str::from_utf8(&buf[..width]).ok()
+            .and_then(|s| s.chars().next())
+            .ok_or_else(|| self.error(ErrorCode::NotUtf8))
+    }
+
+    /// Parses whatever value comes next and hands it to `visitor`. This is
+    /// the entry point used for both typed deserialization and for the
+    /// self-describing `Value` deserializer.
+    fn parse_value<V>(&mut self, mut visitor: V) -> Result<V::Value, Error>
+        where V: de::Visitor,
+    {
+        self.parse_whitespace();
+
+        if self.eof() {
+            return Err(self.error(ErrorCode::EOFWhileParsingValue));
+        }
+
+        match self.ch_or_null() {
+            b'(' => {
+                self.bump();
+                self.parse_whitespace();
+                if self.ch_or_null() != b')' {
+                    return Err(self.error(ErrorCode::ExpectedSomeValue));
+                }
+                self.bump();
+                visitor.visit_unit()
+            }
+            b'[' => self.parse_seq(visitor),
+            b'{' => self.parse_map(visitor),
+            b'"' => {
+                let s = try!(self.parse_string());
+                visitor.visit_string(s)
+            }
+            b'\'' => {
+                let c = try!(self.parse_char());
+                visitor.visit_char(c)
+            }
+            b't' => {
+                try!(self.parse_ident(b"true"));
+                visitor.visit_bool(true)
+            }
+            b'f' => {
+                try!(self.parse_ident(b"false"));
+                visitor.visit_bool(false)
+            }
+            b'N' => {
+                try!(self.parse_ident(b"None"));
+                visitor.visit_none()
+            }
+            b'S' => {
+                try!(self.parse_ident(b"Some"));
+                self.parse_whitespace();
+                if self.ch_or_null() != b'(' {
+                    return Err(self.error(ErrorCode::ExpectedSomeValue));
+                }
+                self.bump();
+
+                let ret = try!(visitor.visit_some(self));
+
+                self.parse_whitespace();
+                if self.ch_or_null() != b')' {
+                    return Err(self.error(ErrorCode::ExpectedSomeValue));
+                }
+                self.bump();
+
+                Ok(ret)
+            }
+            _ if self.looking_at_pilcrow() => {
+                let name = try!(self.parse_pilcrow_name());
+                visitor.visit_string(name)
+            }
+            b'-' | b'0'...b'9' => {
+                match try!(self.parse_number()) {
+                    Number::I64(n) => visitor.visit_i64(n),
+                    Number::U64(n) => visitor.visit_u64(n),
+                    Number::F64(n) => visitor.visit_f64(n),
+                }
+            }
+            _ => Err(self.error(ErrorCode::ExpectedSomeValue)),
+        }
+    }
+
+    fn parse_seq<V>(&mut self, mut visitor: V) -> Result<V::Value, Error>
+        where V: de::Visitor,
+    {
+        self.bump();
+        let ret = try!(visitor.visit_seq(SeqDecoder { decoder: self, first: true }));
+
+        self.parse_whitespace();
+        if self.ch_or_null() != b']' {
+            return Err(self.error(ErrorCode::ExpectedListCommaOrEnd));
+        }
+        self.bump();
+
+        Ok(ret)
+    }
+
+    fn parse_map<V>(&mut self, mut visitor: V) -> Result<V::Value, Error>
+        where V: de::Visitor,
+    {
+        self.bump();
+        let ret = try!(visitor.visit_map(MapDecoder { decoder: self, first: true }));
+
+        self.parse_whitespace();
+        if self.ch_or_null() != b'}' {
+            return Err(self.error(ErrorCode::ExpectedObjectCommaOrEnd));
+        }
+        self.bump();
+
+        Ok(ret)
+    }
+}
+
+enum Number {
+    I64(i64),
+    U64(u64),
+    F64(f64),
+}
+
+/// The number of bytes a UTF-8 encoded codepoint occupies given its leading
+/// byte, or `0` if `byte` can't start a valid UTF-8 sequence.
+fn utf8_char_width(byte: u8) -> usize {
+    match byte {
+        0x00...0x7F => 1,
+        0xC2...0xDF => 2,
+        0xE0...0xEF => 3,
+        0xF0...0xF4 => 4,
+        _ => 0,
+    }
+}
+
+struct SeqDecoder<'a, Iter: 'a + Iterator<Item = io::Result<u8>>> {
+    decoder: &'a mut Decoder<Iter>,
+    first: bool,
+}
+
+impl<'a, Iter> de::SeqVisitor for SeqDecoder<'a, Iter>
+    where Iter: Iterator<Item = io::Result<u8>>,
+{
+    type Error = Error;
+
+    fn visit<T>(&mut self) -> Result<Option<T>, Error>
+        where T: de::Deserialize,
+    {
+        self.decoder.parse_whitespace();
+
+        if self.decoder.ch_or_null() == b']' {
+            return Ok(None);
+        }
+
+        if !self.first {
+            if self.decoder.ch_or_null() != b',' {
+                return Err(self.decoder.error(ErrorCode::ExpectedListCommaOrEnd));
+            }
+            self.decoder.bump();
+            self.decoder.parse_whitespace();
+
+            if self.decoder.ch_or_null() == b']' {
+                return Ok(None);
+            }
+        }
+        self.first = false;
+
+        de::Deserialize::deserialize(self.decoder).map(Some)
+    }
+
+    fn end(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+struct MapDecoder<'a, Iter: 'a + Iterator<Item = io::Result<u8>>> {
+    decoder: &'a mut Decoder<Iter>,
+    first: bool,
+}
+
+impl<'a, Iter> de::MapVisitor for MapDecoder<'a, Iter>
+    where Iter: Iterator<Item = io::Result<u8>>,
+{
+    type Error = Error;
+
+    fn visit_key<K>(&mut self) -> Result<Option<K>, Error>
+        where K: de::Deserialize,
+    {
+        self.decoder.parse_whitespace();
+
+        if self.decoder.ch_or_null() == b'}' {
+            return Ok(None);
+        }
+
+        if !self.first {
+            if self.decoder.ch_or_null() != b',' {
+                return Err(self.decoder.error(ErrorCode::ExpectedObjectCommaOrEnd));
+            }
+            self.decoder.bump();
+            self.decoder.parse_whitespace();
+
+            if self.decoder.ch_or_null() == b'}' {
+                return Ok(None);
+            }
+        }
+        self.first = false;
+
+        de::Deserialize::deserialize(self.decoder).map(Some)
+    }
+
+    fn visit_value<V>(&mut self) -> Result<V, Error>
+        where V: de::Deserialize,
+    {
+        self.decoder.parse_whitespace();
+
+        if self.decoder.ch_or_null() != b':' {
+            return Err(self.decoder.error(ErrorCode::ExpectedColon));
+        }
+        self.decoder.bump();
+
+        de::Deserialize::deserialize(self.decoder)
+    }
+
+    fn end(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl<Iter> de::Deserializer for Decoder<Iter>
+    where Iter: Iterator<Item = io::Result<u8>>,
+{
+    type Error = Error;
+
+    fn visit<V>(&mut self, visitor: V) -> Result<V::Value, Error>
+        where V: de::Visitor,
+    {
+        self.parse_value(visitor)
+    }
+
+    fn visit_option<V>(&mut self, visitor: V) -> Result<V::Value, Error>
+        where V: de::Visitor,
+    {
+        self.parse_value(visitor)
+    }
+
+    fn format() -> &'static str {
+        "ron"
+    }
+}
+
+fn from_trait<Iter, T>(rdr: Iter) -> Result<T, Error>
+    where Iter: Iterator<Item = io::Result<u8>>,
+          T: de::Deserialize,
+{
+    let mut decoder = Decoder::new(rdr);
+    let value = try!(de::Deserialize::deserialize(&mut decoder));
+
+    decoder.parse_whitespace();
+    if !decoder.eof() {
+        return Err(decoder.error(ErrorCode::TrailingCharacters));
+    }
+
+    Ok(value)
+}
+
+/// Decodes a RON value from an `Iterator` of bytes.
+pub fn from_iter<Iter, T>(iter: Iter) -> Result<T, Error>
+    where Iter: Iterator<Item = io::Result<u8>>,
+          T: de::Deserialize,
+{
+    from_trait(iter)
+}
+
+/// Decodes a RON value from an `io::Read`.
+pub fn from_reader<R, T>(rdr: R) -> Result<T, Error>
+    where R: io::Read,
+          T: de::Deserialize,
+{
+    from_trait(rdr.bytes())
+}
+
+/// Decodes a RON value from a byte slice.
+pub fn from_slice<T>(v: &[u8]) -> Result<T, Error>
+    where T: de::Deserialize,
+{
+    from_trait(v.iter().map(|byte| Ok(*byte)))
+}
+
+/// Decodes a RON value from a `&str`.
+pub fn from_str<T>(s: &str) -> Result<T, Error>
+    where T: de::Deserialize,
+{
+    from_slice(s.as_bytes())
+}
@@ -1,17 +1,26 @@
 extern crate serde;
 
-use std::fmt;
 use std::io;
 
-use std::string::FromUtf8Error;
-
 use serde::ser;
 
+use error::Error;
+pub use format::{Formatter, CompactFormatter, PrettyFormatter};
+use format::CharEscape;
+
 /// A structure for implementing serialization to RON.
 pub struct Encoder<W, F = CompactFormatter> {
     writer: W,
     formatter: F,
-    first_line: bool,
+    /// One entry per currently-open seq/map, tracking whether the next
+    /// element written into it is the first one. Kept as a stack (rather
+    /// than a single flag) so that a nested seq/map doesn't clobber the
+    /// "is first" state of the container it's nested inside.
+    stack: Vec<Compound>,
+}
+
+struct Compound {
+    first: bool,
 }
 
 impl<W> Encoder<W>
@@ -40,48 +49,68 @@ impl<W, F> Encoder<W, F>
         Encoder {
             writer: writer,
             formatter: formatter,
-            first_line: false,
+            stack: Vec::new(),
         }
     }
 
-    fn emit_constant<T: fmt::Display>(&mut self, v: T) -> io::Result<()> {
-        write!(self.writer, "{}", v)
+    /// Unwraps the `Encoder`, returning the underlying writer so it can be
+    /// reused, e.g. to serialize several top-level values into one stream.
+    pub fn into_inner(self) -> W {
+        self.writer
     }
 
-    fn emit_escape<T: fmt::Display>(&mut self, v: T, escape: char) -> io::Result<()> {
+    fn emit_escape<T: ::std::fmt::Display>(&mut self, v: T, escape: char) -> io::Result<()> {
         write!(self.writer, "{}{}{}", escape, v, escape)
     }
 
+    fn emit_str(&mut self, v: &str) -> io::Result<()> {
+        format_escaped_str(&mut self.writer, &mut self.formatter, v)
+    }
+
+    fn emit_char(&mut self, v: char) -> io::Result<()> {
+        format_escaped_char(&mut self.writer, &mut self.formatter, v)
+    }
+
+    /// Returns whether the element about to be written into the
+    /// innermost open container is the first one, and marks it as no
+    /// longer first.
+    fn take_first(&mut self) -> bool {
+        let top = self.stack.last_mut().expect("comma written outside of an open seq/map");
+        let first = top.first;
+        top.first = false;
+        first
+    }
+
 }
 
-impl<W, F> ser::Serializer for Encoder<W, F>
+impl<'a, W, F> ser::Serializer for &'a mut Encoder<W, F>
     where W: io::Write,
           F: Formatter,
 {
     type Error = io::Error;
 
-    fn visit_bool(&mut self, v: bool)    -> io::Result<()> { self.emit_constant(v) }
+    fn visit_bool(&mut self, v: bool)    -> io::Result<()> { self.formatter.write_bool(&mut self.writer, v) }
 
-    fn visit_usize(&mut self, v: usize)  -> io::Result<()> { self.emit_constant(v) }
-    fn visit_u64(&mut self, v: u64)      -> io::Result<()> { self.emit_constant(v) }
-    fn visit_u32(&mut self, v: u32)      -> io::Result<()> { self.emit_constant(v) }
-    fn visit_u16(&mut self, v: u16)      -> io::Result<()> { self.emit_constant(v) }
-    fn visit_u8(&mut self, v: u8)        -> io::Result<()> { self.emit_constant(v) }
+    fn visit_usize(&mut self, v: usize)  -> io::Result<()> { self.formatter.write_usize(&mut self.writer, v) }
+    fn visit_u64(&mut self, v: u64)      -> io::Result<()> { self.formatter.write_u64(&mut self.writer, v) }
+    fn visit_u32(&mut self, v: u32)      -> io::Result<()> { self.formatter.write_u32(&mut self.writer, v) }
+    fn visit_u16(&mut self, v: u16)      -> io::Result<()> { self.formatter.write_u16(&mut self.writer, v) }
+    fn visit_u8(&mut self, v: u8)        -> io::Result<()> { self.formatter.write_u8(&mut self.writer, v) }
 
-    fn visit_isize(&mut self, v: isize)  -> io::Result<()> { self.emit_constant(v) }
-    fn visit_i64(&mut self, v: i64)      -> io::Result<()> { self.emit_constant(v) }
-    fn visit_i32(&mut self, v: i32)      -> io::Result<()> { self.emit_constant(v) }
-    fn visit_i16(&mut self, v: i16)      -> io::Result<()> { self.emit_constant(v) }
-    fn visit_i8(&mut self, v: i8)        -> io::Result<()> { self.emit_constant(v) }
+    fn visit_isize(&mut self, v: isize)  -> io::Result<()> { self.formatter.write_isize(&mut self.writer, v) }
+    fn visit_i64(&mut self, v: i64)      -> io::Result<()> { self.formatter.write_i64(&mut self.writer, v) }
+    fn visit_i32(&mut self, v: i32)      -> io::Result<()> { self.formatter.write_i32(&mut self.writer, v) }
+    fn visit_i16(&mut self, v: i16)      -> io::Result<()> { self.formatter.write_i16(&mut self.writer, v) }
+    fn visit_i8(&mut self, v: i8)        -> io::Result<()> { self.formatter.write_i8(&mut self.writer, v) }
 
-    fn visit_f64(&mut self, v: f64)      -> io::Result<()> { self.emit_constant(v) }
-    fn visit_f32(&mut self, v: f32)      -> io::Result<()> { self.emit_constant(v) }
+    fn visit_f64(&mut self, v: f64)      -> io::Result<()> { self.formatter.write_f64(&mut self.writer, v) }
+    fn visit_f32(&mut self, v: f32)      -> io::Result<()> { self.formatter.write_f32(&mut self.writer, v) }
 
-    fn visit_char(&mut self, v: char)    -> io::Result<()> { self.emit_escape(v, '\'') }
-    fn visit_str(&mut self, v: &str)     -> io::Result<()> { self.emit_escape(v, '\"') }
+    fn visit_char(&mut self, v: char)    -> io::Result<()> { self.emit_char(v) }
+    fn visit_str(&mut self, v: &str)     -> io::Result<()> { self.emit_str(v) }
 
     fn visit_unit(&mut self) -> io::Result<()> {
-        self.writer.write_all(b"()")
+        self.formatter.write_null(&mut self.writer)
     }
 
     fn visit_none(&mut self) -> io::Result<()> {
@@ -107,11 +136,21 @@ impl<W, F> ser::Serializer for Encoder<W, F>
             },
             _ => {
                 try!(self.formatter.open(&mut self.writer, b'['));
-                self.first_line = true;
+                self.stack.push(Compound { first: true });
+
+                // The visitor writes one element per call and returns
+                // `None` once it has none left.
+                let mut result: io::Result<()> = Ok(());
+                loop {
+                    match visitor.visit(self) {
+                        Ok(Some(())) => {}
+                        Ok(None) => break,
+                        Err(err) => { result = Err(err); break; }
+                    }
+                }
+                self.stack.pop();
+                try!(result);
 
-                // TODO: maybe fix
-                try!(visitor.visit(self));
- 
                 self.formatter.close(&mut self.writer, b']')
             }
         }
@@ -131,8 +170,8 @@ impl<W, F> ser::Serializer for Encoder<W, F>
     fn visit_seq_elt<T>(&mut self, value: T) -> io::Result<()>
         where T: ser::Serialize
     {
-        try!(self.formatter.comma(&mut self.writer, self.first_line));
-        self.first_line = true;
+        let first = self.take_first();
+        try!(self.formatter.comma(&mut self.writer, first));
 
         value.serialize(self)
     }
@@ -146,9 +185,18 @@ impl<W, F> ser::Serializer for Encoder<W, F>
             }
             _ => {
                 try!(self.formatter.open(&mut self.writer, b'{'));
-                self.first_line = true;
-
-                /*while let Some(()) = */try!(visitor.visit(self)); //{ }
+                self.stack.push(Compound { first: true });
+
+                let mut result: io::Result<()> = Ok(());
+                loop {
+                    match visitor.visit(self) {
+                        Ok(Some(())) => {}
+                        Ok(None) => break,
+                        Err(err) => { result = Err(err); break; }
+                    }
+                }
+                self.stack.pop();
+                try!(result);
 
                 self.formatter.close(&mut self.writer, b'}')
             }
@@ -171,8 +219,8 @@ impl<W, F> ser::Serializer for Encoder<W, F>
         where K: ser::Serialize,
               V: ser::Serialize,
     {
-        try!(self.formatter.comma(&mut self.writer, self.first_line));
-        self.first_line = false;
+        let first = self.take_first();
+        try!(self.formatter.comma(&mut self.writer, first));
 
         try!(key.serialize(self));
         try!(self.formatter.colon(&mut self.writer));
@@ -185,115 +233,76 @@ impl<W, F> ser::Serializer for Encoder<W, F>
 
 }
 
-pub trait Formatter {
-    fn open<W>(&mut self, writer: &mut W, ch: u8) -> io::Result<()>
-        where W: io::Write;
-
-    fn comma<W>(&mut self, writer: &mut W, first: bool) -> io::Result<()>
-        where W: io::Write;
-
-    fn colon<W>(&mut self, writer: &mut W) -> io::Result<()>
-        where W: io::Write;
-
-    fn close<W>(&mut self, writer: &mut W, ch: u8) -> io::Result<()>
-        where W: io::Write;
-}
-
-pub struct CompactFormatter;
-
-impl Formatter for CompactFormatter {
-    fn open<W>(&mut self, writer: &mut W, ch: u8) -> io::Result<()>
-        where W: io::Write,
-    {
-        writer.write_all(&[ch])
-    }
-
-    fn comma<W>(&mut self, writer: &mut W, first: bool) -> io::Result<()>
-        where W: io::Write,
-    {
-        if first {
-            Ok(())
-        } else {
-            writer.write_all(b",")
-        }
-    }
-
-    fn colon<W>(&mut self, writer: &mut W) -> io::Result<()>
-        where W: io::Write,
-    {
-        writer.write_all(b":")
-    }
-
-    fn close<W>(&mut self, writer: &mut W, ch: u8) -> io::Result<()>
-        where W: io::Write,
-    {
-        writer.write_all(&[ch])
-    }
-}
-
-pub struct PrettyFormatter<'a> {
-    current_indent: usize,
-    indent: &'a [u8],
+fn format_escaped_str<W, F>(writer: &mut W, formatter: &mut F, value: &str) -> io::Result<()>
+    where W: io::Write,
+          F: Formatter,
+{
+    try!(formatter.begin_string(writer));
+    try!(format_escaped_str_contents(writer, formatter, value));
+    formatter.end_string(writer)
 }
 
-impl<'a> PrettyFormatter<'a> {
-    fn new() -> Self {
-        PrettyFormatter::with_indent(b"  ")
-    }
-
-    fn with_indent(indent: &'a [u8]) -> Self {
-        PrettyFormatter {
-            current_indent: 0,
-            indent: indent,
+fn format_escaped_str_contents<W, F>(writer: &mut W, formatter: &mut F, value: &str) -> io::Result<()>
+    where W: io::Write,
+          F: Formatter,
+{
+    let bytes = value.as_bytes();
+
+    let mut start = 0;
+
+    for (i, &byte) in bytes.iter().enumerate() {
+        let escape = match byte {
+            b'"' => CharEscape::Quote,
+            b'\\' => CharEscape::ReverseSolidus,
+            b'\n' => CharEscape::LineFeed,
+            b'\r' => CharEscape::CarriageReturn,
+            b'\t' => CharEscape::Tab,
+            0x08 => CharEscape::Backspace,
+            0x0C => CharEscape::FormFeed,
+            byte if byte < 0x20 => CharEscape::AsciiControl(byte),
+            _ => continue,
+        };
+
+        if start < i {
+            try!(formatter.write_string_fragment(writer, &value[start..i]));
         }
-    }
-}
-
-impl<'a> Formatter for PrettyFormatter<'a> {
-    fn open<W>(&mut self, writer: &mut W, ch: u8) -> io::Result<()>
-        where W: io::Write,
-    {
-        self.current_indent += 1;
-        writer.write_all(&[ch])
-    }
 
-    fn comma<W>(&mut self, writer: &mut W, first: bool) -> io::Result<()>
-        where W: io::Write,
-    {
-        if first {
-            try!(writer.write_all(b"\n"));
-        } else {
-            try!(writer.write_all(b",\n"));
-        }
+        try!(formatter.write_char_escape(writer, escape));
 
-        indent(writer, self.current_indent, self.indent)
+        start = i + 1;
     }
 
-    fn colon<W>(&mut self, writer: &mut W) -> io::Result<()>
-        where W: io::Write,
-    {
-        writer.write_all(b": ")
+    if start != bytes.len() {
+        try!(formatter.write_string_fragment(writer, &value[start..]));
     }
 
-    fn close<W>(&mut self, writer: &mut W, ch: u8) -> io::Result<()>
-        where W: io::Write,
-    {
-        self.current_indent -= 1;
-        try!(writer.write(b"\n"));
-        try!(indent(writer, self.current_indent, self.indent));
-
-        writer.write_all(&[ch])
-    }
+    Ok(())
 }
 
-fn indent<W>(wr: &mut W, n: usize, s: &[u8]) -> io::Result<()>
+fn format_escaped_char<W, F>(writer: &mut W, formatter: &mut F, value: char) -> io::Result<()>
     where W: io::Write,
+          F: Formatter,
 {
-    for _ in 0 .. n {
-        try!(wr.write_all(s));
+    try!(formatter.begin_char(writer));
+
+    let escape = match value {
+        '\'' => Some(CharEscape::SingleQuote),
+        '\\' => Some(CharEscape::ReverseSolidus),
+        '\n' => Some(CharEscape::LineFeed),
+        '\r' => Some(CharEscape::CarriageReturn),
+        '\t' => Some(CharEscape::Tab),
+        '\u{8}' => Some(CharEscape::Backspace),
+        '\u{c}' => Some(CharEscape::FormFeed),
+        c if (c as u32) < 0x20 => Some(CharEscape::AsciiControl(c as u8)),
+        _ => None,
+    };
+
+    match escape {
+        Some(escape) => try!(formatter.write_char_escape(writer, escape)),
+        None => try!(formatter.write_string_fragment(writer, &value.to_string())),
     }
 
-    Ok(())
+    formatter.end_char(writer)
 }
 
 #[inline]
@@ -302,7 +311,7 @@ pub fn to_writer<W, T>(writer: &mut W, value: &T) -> io::Result<()>
           T: ser::Serialize,
 {
     let mut enc = Encoder::new(writer);
-    try!(value.serialize(&mut enc));
+    try!(value.serialize(&mut &mut enc));
     Ok(())
 }
 
@@ -312,48 +321,91 @@ pub fn to_writer_pretty<W, T>(writer: &mut W, value: &T) -> io::Result<()>
           T: ser::Serialize,
 {
     let mut enc = Encoder::pretty(writer);
-    try!(value.serialize(&mut enc));
+    try!(value.serialize(&mut &mut enc));
     Ok(())
 }
 
-/// Encode the specified struct into a json `[u8]` buffer.
+/// Encode the specified struct into a RON `[u8]` buffer.
+///
+/// Writing to a `Vec` can't fail with an IO error, but serializing a
+/// non-finite float still can, so this still returns a `Result`.
 #[inline]
-pub fn to_vec<T>(value: &T) -> Vec<u8>
+pub fn to_vec<T>(value: &T) -> Result<Vec<u8>, Error>
     where T: ser::Serialize,
 {
-    // We are writing to a Vec, which doesn't fail. So we can ignore
-    // the error.
     let mut writer = Vec::with_capacity(128);
-    to_writer(&mut writer, value).unwrap();
-    writer
+    try!(to_writer(&mut writer, value));
+    Ok(writer)
 }
 
-/// Encode the specified struct into a json `[u8]` buffer.
+/// Encode the specified struct into a RON `[u8]` buffer, pretty-printed.
 #[inline]
-pub fn to_vec_pretty<T>(value: &T) -> Vec<u8>
+pub fn to_vec_pretty<T>(value: &T) -> Result<Vec<u8>, Error>
     where T: ser::Serialize,
 {
-    // We are writing to a Vec, which doesn't fail. So we can ignore
-    // the error.
     let mut writer = Vec::with_capacity(128);
-    to_writer_pretty(&mut writer, value).unwrap();
-    writer
+    try!(to_writer_pretty(&mut writer, value));
+    Ok(writer)
 }
 
-/// Encode the specified struct into a json `String` buffer.
+/// Encode the specified struct into a RON `String`.
 #[inline]
-pub fn to_string<T>(value: &T) -> Result<String, FromUtf8Error>
+pub fn to_string<T>(value: &T) -> Result<String, Error>
     where T: ser::Serialize
 {
-    let vec = to_vec(value);
-    String::from_utf8(vec)
+    let vec = try!(to_vec(value));
+    Ok(try!(String::from_utf8(vec)))
 }
 
-/// Encode the specified struct into a json `String` buffer.
+/// Encode the specified struct into a RON `String`, pretty-printed.
 #[inline]
-pub fn to_string_pretty<T>(value: &T) -> Result<String, FromUtf8Error>
+pub fn to_string_pretty<T>(value: &T) -> Result<String, Error>
     where T: ser::Serialize
 {
-    let vec = to_vec_pretty(value);
-    String::from_utf8(vec)
+    let vec = try!(to_vec_pretty(value));
+    Ok(try!(String::from_utf8(vec)))
+}
+
+#[cfg(test)]
+mod tests {
+    use ::decode::from_str;
+    use super::to_string;
+
+    fn round_trip_str(s: &str) {
+        let encoded = to_string(&s.to_owned()).unwrap();
+        let decoded: String = from_str(&encoded).unwrap();
+        assert_eq!(decoded, s);
+    }
+
+    fn round_trip_char(c: char) {
+        let encoded = to_string(&c).unwrap();
+        let decoded: char = from_str(&encoded).unwrap();
+        assert_eq!(decoded, c);
+    }
+
+    #[test]
+    fn string_escaping_round_trips() {
+        round_trip_str("");
+        round_trip_str("plain");
+        round_trip_str("with \"double quotes\"");
+        round_trip_str("with 'single quotes'");
+        round_trip_str("back\\slash");
+        round_trip_str("line\nfeed\tand\rcarriage");
+        round_trip_str("bell\u{8}form\u{c}feed");
+        round_trip_str("unicode: \u{e9}\u{1f600}");
+    }
+
+    #[test]
+    fn char_escaping_round_trips() {
+        round_trip_char('a');
+        round_trip_char('\'');
+        round_trip_char('"');
+        round_trip_char('\\');
+        round_trip_char('\n');
+        round_trip_char('\r');
+        round_trip_char('\t');
+        round_trip_char('\u{8}');
+        round_trip_char('\u{c}');
+        round_trip_char('\u{e9}');
+    }
 }
@@ -1,6 +1,7 @@
 use std::error;
 use std::fmt;
 use std::io;
+use std::string::FromUtf8Error;
 
 use serde::de;
 
@@ -13,6 +14,10 @@ pub enum ErrorCode {
     InvalidNumber,
     InvalidUnicodeCodePoint,
 
+    /// A `NaN`, `inf` or `-inf` float was passed to the encoder. RON has no
+    /// token to represent non-finite floats, so they cannot be emitted.
+    NonFiniteFloat,
+
     NotFourDigit,
     NotUtf8,
 
@@ -76,6 +81,7 @@ impl fmt::Debug for ErrorCode {
             ErrorCode::LoneLeadingSurrogateInHexEscape => "lone leading surrogate in hex escape".fmt(f),
             ErrorCode::UnknownField(ref field) => write!(f, "unknown field \"{}\"", field),
             ErrorCode::MissingField(ref field) => write!(f, "missing field \"{}\"", field),
+            ErrorCode::NonFiniteFloat => "NaN and infinite floats cannot be represented in RON".fmt(f),
             ErrorCode::NotFourDigit => "invalid \\u escape (not four digits)".fmt(f),
             ErrorCode::NotUtf8 => "contents not utf-8".fmt(f),
             ErrorCode::TrailingCharacters => "trailing characters".fmt(f),
@@ -93,6 +99,26 @@ pub enum Error {
     MissingField(&'static str)
 }
 
+impl Error {
+    /// The line of the input the error occurred on, or `0` if the error
+    /// isn't a `Syntax` error.
+    pub fn line(&self) -> usize {
+        match *self {
+            Error::Syntax(_, line, _) => line,
+            _ => 0,
+        }
+    }
+
+    /// The column of the input the error occurred on, or `0` if the error
+    /// isn't a `Syntax` error.
+    pub fn column(&self) -> usize {
+        match *self {
+            Error::Syntax(_, _, col) => col,
+            _ => 0,
+        }
+    }
+}
+
 impl error::Error for Error {
     fn description(&self) -> &str {
         match *self {
@@ -125,9 +151,43 @@ impl fmt::Display for Error {
     }
 }
 
+/// A marker `std::error::Error` that `format::check_finite` wraps a
+/// non-finite float rejection in, since `Encoder`'s `Serializer::Error` is
+/// fixed to `io::Error`. `From<io::Error> for Error` downcasts for this
+/// marker so a non-finite float still surfaces as a real
+/// `Error::Syntax(ErrorCode::NonFiniteFloat, ..)` instead of an opaque IO
+/// error.
+#[derive(Debug)]
+pub struct NonFiniteFloatError;
+
+impl fmt::Display for NonFiniteFloatError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&ErrorCode::NonFiniteFloat, f)
+    }
+}
+
+impl error::Error for NonFiniteFloatError {
+    fn description(&self) -> &str {
+        "NaN and infinite floats cannot be represented in RON"
+    }
+}
+
 impl From<io::Error> for Error {
     fn from(error: io::Error) -> Error {
-        Error::Io(error)
+        let is_non_finite = error.get_ref()
+            .map_or(false, |e| e.downcast_ref::<NonFiniteFloatError>().is_some());
+
+        if is_non_finite {
+            Error::Syntax(ErrorCode::NonFiniteFloat, 0, 0)
+        } else {
+            Error::Io(error)
+        }
+    }
+}
+
+impl From<FromUtf8Error> for Error {
+    fn from(_: FromUtf8Error) -> Error {
+        Error::Syntax(ErrorCode::NotUtf8, 0, 0)
     }
 }
 
@@ -141,7 +201,8 @@ impl From<de::value::Error> for Error {
                 de::Error::end_of_stream_error()
             }
             de::value::Error::UnknownFieldError(field) => {
-                Error::Syntax(ErrorCode::UnknownField(field), 0, 0)
+                let (line, col) = ::decode::current_position();
+                Error::Syntax(ErrorCode::UnknownField(field), line, col)
             }
             de::value::Error::MissingFieldError(field) => {
                 de::Error::missing_field_error(field)
@@ -152,15 +213,18 @@ impl From<de::value::Error> for Error {
 
 impl de::Error for Error {
     fn syntax_error() -> Error {
-        Error::Syntax(ErrorCode::ExpectedSomeValue, 0, 0)
+        let (line, col) = ::decode::current_position();
+        Error::Syntax(ErrorCode::ExpectedSomeValue, line, col)
     }
 
     fn end_of_stream_error() -> Error {
-        Error::Syntax(ErrorCode::EOFWhileParsingValue, 0, 0)
+        let (line, col) = ::decode::current_position();
+        Error::Syntax(ErrorCode::EOFWhileParsingValue, line, col)
     }
 
     fn unknown_field_error(field: &str) -> Error {
-        Error::Syntax(ErrorCode::UnknownField(field.to_string()), 0, 0)
+        let (line, col) = ::decode::current_position();
+        Error::Syntax(ErrorCode::UnknownField(field.to_string()), line, col)
     }
 
     fn missing_field_error(field: &'static str) -> Error {
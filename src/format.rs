@@ -0,0 +1,306 @@
+extern crate itoa;
+extern crate ryu;
+
+use std::io;
+use std::num::FpCategory;
+
+use error::NonFiniteFloatError;
+
+/// A single character that requires escaping inside a RON string or char
+/// literal.
+pub enum CharEscape {
+    /// A double quote `"`
+    Quote,
+    /// A single quote `'`
+    SingleQuote,
+    /// A backslash `\`
+    ReverseSolidus,
+    /// A backspace character
+    Backspace,
+    /// A form feed character
+    FormFeed,
+    /// A line feed character
+    LineFeed,
+    /// A carriage return character
+    CarriageReturn,
+    /// A tab character
+    Tab,
+    /// An ASCII control character `\u00XX` that isn't one of the above
+    AsciiControl(u8),
+}
+
+/// This trait abstracts away serializing the RON control characters, which
+/// allows the user to optionally pretty print the RON output, as well as
+/// tweak how individual scalars (numbers, strings, bools, ...) get written
+/// without having to touch `Encoder` itself.
+///
+/// `open`, `comma`, `colon` and `close` have to be implemented; every other
+/// method has a default implementation that produces RON's compact form, so
+/// a `Formatter` only needs to override the scalar methods it actually
+/// wants to customize.
+pub trait Formatter {
+    fn open<W>(&mut self, writer: &mut W, ch: u8) -> io::Result<()>
+        where W: io::Write;
+
+    fn comma<W>(&mut self, writer: &mut W, first: bool) -> io::Result<()>
+        where W: io::Write;
+
+    fn colon<W>(&mut self, writer: &mut W) -> io::Result<()>
+        where W: io::Write;
+
+    fn close<W>(&mut self, writer: &mut W, ch: u8) -> io::Result<()>
+        where W: io::Write;
+
+    /// Writes a `()` to the specified writer.
+    fn write_null<W>(&mut self, writer: &mut W) -> io::Result<()>
+        where W: io::Write,
+    {
+        writer.write_all(b"()")
+    }
+
+    /// Writes a `true` or `false` value to the specified writer.
+    fn write_bool<W>(&mut self, writer: &mut W, value: bool) -> io::Result<()>
+        where W: io::Write,
+    {
+        writer.write_all(if value { b"true" } else { b"false" })
+    }
+
+    fn write_i8<W>(&mut self, writer: &mut W, value: i8) -> io::Result<()>
+        where W: io::Write,
+    { itoa::write(writer, value).map(|_| ()) }
+
+    fn write_i16<W>(&mut self, writer: &mut W, value: i16) -> io::Result<()>
+        where W: io::Write,
+    { itoa::write(writer, value).map(|_| ()) }
+
+    fn write_i32<W>(&mut self, writer: &mut W, value: i32) -> io::Result<()>
+        where W: io::Write,
+    { itoa::write(writer, value).map(|_| ()) }
+
+    fn write_i64<W>(&mut self, writer: &mut W, value: i64) -> io::Result<()>
+        where W: io::Write,
+    { itoa::write(writer, value).map(|_| ()) }
+
+    fn write_isize<W>(&mut self, writer: &mut W, value: isize) -> io::Result<()>
+        where W: io::Write,
+    { itoa::write(writer, value).map(|_| ()) }
+
+    fn write_u8<W>(&mut self, writer: &mut W, value: u8) -> io::Result<()>
+        where W: io::Write,
+    { itoa::write(writer, value).map(|_| ()) }
+
+    fn write_u16<W>(&mut self, writer: &mut W, value: u16) -> io::Result<()>
+        where W: io::Write,
+    { itoa::write(writer, value).map(|_| ()) }
+
+    fn write_u32<W>(&mut self, writer: &mut W, value: u32) -> io::Result<()>
+        where W: io::Write,
+    { itoa::write(writer, value).map(|_| ()) }
+
+    fn write_u64<W>(&mut self, writer: &mut W, value: u64) -> io::Result<()>
+        where W: io::Write,
+    { itoa::write(writer, value).map(|_| ()) }
+
+    fn write_usize<W>(&mut self, writer: &mut W, value: usize) -> io::Result<()>
+        where W: io::Write,
+    { itoa::write(writer, value).map(|_| ()) }
+
+    fn write_f32<W>(&mut self, writer: &mut W, value: f32) -> io::Result<()>
+        where W: io::Write,
+    {
+        try!(check_finite(value.classify()));
+
+        let mut buffer = ryu::Buffer::new();
+        writer.write_all(buffer.format(value).as_bytes())
+    }
+
+    fn write_f64<W>(&mut self, writer: &mut W, value: f64) -> io::Result<()>
+        where W: io::Write,
+    {
+        try!(check_finite(value.classify()));
+
+        let mut buffer = ryu::Buffer::new();
+        writer.write_all(buffer.format(value).as_bytes())
+    }
+
+    /// Called before each series of `write_string_fragment` and
+    /// `write_char_escape`. Writes a `"` to the specified writer.
+    fn begin_string<W>(&mut self, writer: &mut W) -> io::Result<()>
+        where W: io::Write,
+    {
+        writer.write_all(b"\"")
+    }
+
+    /// Called after each series of `write_string_fragment` and
+    /// `write_char_escape`. Writes a `"` to the specified writer.
+    fn end_string<W>(&mut self, writer: &mut W) -> io::Result<()>
+        where W: io::Write,
+    {
+        writer.write_all(b"\"")
+    }
+
+    /// Called before a char literal's (possibly escaped) contents. Writes a
+    /// `'` to the specified writer.
+    fn begin_char<W>(&mut self, writer: &mut W) -> io::Result<()>
+        where W: io::Write,
+    {
+        writer.write_all(b"'")
+    }
+
+    /// Called after a char literal's (possibly escaped) contents. Writes a
+    /// `'` to the specified writer.
+    fn end_char<W>(&mut self, writer: &mut W) -> io::Result<()>
+        where W: io::Write,
+    {
+        writer.write_all(b"'")
+    }
+
+    /// Writes a string fragment that doesn't need any escaping.
+    fn write_string_fragment<W>(&mut self, writer: &mut W, fragment: &str) -> io::Result<()>
+        where W: io::Write,
+    {
+        writer.write_all(fragment.as_bytes())
+    }
+
+    /// Writes a character escape for a string or char literal.
+    fn write_char_escape<W>(&mut self, writer: &mut W, escape: CharEscape) -> io::Result<()>
+        where W: io::Write,
+    {
+        use self::CharEscape::*;
+
+        let s = match escape {
+            Quote => b"\\\"" as &[u8],
+            SingleQuote => b"\\'",
+            ReverseSolidus => b"\\\\",
+            Backspace => b"\\b",
+            FormFeed => b"\\f",
+            LineFeed => b"\\n",
+            CarriageReturn => b"\\r",
+            Tab => b"\\t",
+            AsciiControl(byte) => {
+                static HEX_DIGITS: [u8; 16] = *b"0123456789abcdef";
+                let bytes = &[
+                    b'\\', b'u', b'0', b'0',
+                    HEX_DIGITS[(byte >> 4) as usize],
+                    HEX_DIGITS[(byte & 0xF) as usize],
+                ];
+                return writer.write_all(bytes);
+            }
+        };
+
+        writer.write_all(s)
+    }
+}
+
+/// This structure compacts a RON value with no extra whitespace.
+pub struct CompactFormatter;
+
+impl Formatter for CompactFormatter {
+    fn open<W>(&mut self, writer: &mut W, ch: u8) -> io::Result<()>
+        where W: io::Write,
+    {
+        writer.write_all(&[ch])
+    }
+
+    fn comma<W>(&mut self, writer: &mut W, first: bool) -> io::Result<()>
+        where W: io::Write,
+    {
+        if first {
+            Ok(())
+        } else {
+            writer.write_all(b",")
+        }
+    }
+
+    fn colon<W>(&mut self, writer: &mut W) -> io::Result<()>
+        where W: io::Write,
+    {
+        writer.write_all(b":")
+    }
+
+    fn close<W>(&mut self, writer: &mut W, ch: u8) -> io::Result<()>
+        where W: io::Write,
+    {
+        writer.write_all(&[ch])
+    }
+}
+
+/// This structure pretty-prints a RON value to make it more human-readable.
+pub struct PrettyFormatter<'a> {
+    current_indent: usize,
+    indent: &'a [u8],
+}
+
+impl<'a> PrettyFormatter<'a> {
+    /// Constructs a pretty-formatter that uses two spaces for indentation.
+    pub fn new() -> Self {
+        PrettyFormatter::with_indent(b"  ")
+    }
+
+    /// Constructs a pretty-formatter that uses the given indentation
+    /// sequence.
+    pub fn with_indent(indent: &'a [u8]) -> Self {
+        PrettyFormatter {
+            current_indent: 0,
+            indent: indent,
+        }
+    }
+}
+
+impl<'a> Formatter for PrettyFormatter<'a> {
+    fn open<W>(&mut self, writer: &mut W, ch: u8) -> io::Result<()>
+        where W: io::Write,
+    {
+        self.current_indent += 1;
+        writer.write_all(&[ch])
+    }
+
+    fn comma<W>(&mut self, writer: &mut W, first: bool) -> io::Result<()>
+        where W: io::Write,
+    {
+        if first {
+            try!(writer.write_all(b"\n"));
+        } else {
+            try!(writer.write_all(b",\n"));
+        }
+
+        indent(writer, self.current_indent, self.indent)
+    }
+
+    fn colon<W>(&mut self, writer: &mut W) -> io::Result<()>
+        where W: io::Write,
+    {
+        writer.write_all(b": ")
+    }
+
+    fn close<W>(&mut self, writer: &mut W, ch: u8) -> io::Result<()>
+        where W: io::Write,
+    {
+        self.current_indent -= 1;
+        try!(writer.write(b"\n"));
+        try!(indent(writer, self.current_indent, self.indent));
+
+        writer.write_all(&[ch])
+    }
+}
+
+/// RON has no token for `NaN`/`inf`/`-inf`, so a float that doesn't round-trip
+/// through the grammar is reported as an error rather than silently emitted.
+fn check_finite(category: FpCategory) -> io::Result<()> {
+    match category {
+        FpCategory::Nan | FpCategory::Infinite => {
+            Err(io::Error::new(io::ErrorKind::InvalidData, NonFiniteFloatError))
+        }
+        _ => Ok(()),
+    }
+}
+
+fn indent<W>(wr: &mut W, n: usize, s: &[u8]) -> io::Result<()>
+    where W: io::Write,
+{
+    for _ in 0 .. n {
+        try!(wr.write_all(s));
+    }
+
+    Ok(())
+}
@@ -1,4 +1,6 @@
 extern crate serde;
+extern crate itoa;
+extern crate ryu;
 
 use std::iter;
 
@@ -6,12 +8,15 @@ pub mod encode;
 pub mod decode;
 pub mod format;
 pub mod error;
+pub mod value;
 
 pub use self::error::{
     Error,
     ErrorCode
 };
 
+pub use self::value::Value;
+
 pub use self::decode::{
     Decoder,
     from_iter,
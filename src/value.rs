@@ -0,0 +1,326 @@
+extern crate serde;
+
+#[cfg(feature = "preserve_order")]
+extern crate linked_hash_map;
+
+use std::collections::BTreeMap;
+use std::cmp::Ordering;
+use std::hash::{Hash, Hasher};
+
+use serde::{ser, de};
+
+use error::Error;
+
+#[cfg(not(feature = "preserve_order"))]
+/// The backing container for `Value::Map`. Without the `preserve_order`
+/// feature this is a plain `BTreeMap`, so keys end up sorted rather than in
+/// their original document order.
+pub type Map = BTreeMap<Value, Value>;
+
+#[cfg(feature = "preserve_order")]
+/// The backing container for `Value::Map`. With the `preserve_order`
+/// feature enabled, a decoded document re-encodes with its keys in the
+/// order they were written.
+pub type Map = self::linked_hash_map::LinkedHashMap<Value, Value>;
+
+/// An untyped RON value.
+///
+/// `Value` can represent any RON document without requiring a matching
+/// Rust type to deserialize into, which is useful for editing or
+/// transforming documents whose shape isn't known up front.
+#[derive(Clone, Debug)]
+pub enum Value {
+    /// The unit value `()`
+    Unit,
+    Bool(bool),
+    I64(i64),
+    U64(u64),
+    F64(f64),
+    Char(char),
+    String(String),
+    Seq(Vec<Value>),
+    Map(Map),
+    /// A named enum variant or unit struct. `Serialize` emits a unit variant
+    /// as `¶Name¶` and a variant carrying data as `{"Name":[data]}` (the
+    /// seq-wrapped form `visit_enum_seq` produces). On the way back in, only
+    /// the data-carrying form round-trips to `EnumVariant(Name, Some(data))`
+    /// — the decoder has no self-describing way to tell a generic `Visitor`
+    /// that `¶Name¶` is anything other than a string, so it decodes as
+    /// `Value::String(Name)` instead of `EnumVariant(Name, None)`.
+    EnumVariant(String, Option<Box<Value>>),
+}
+
+impl PartialEq for Value {
+    fn eq(&self, other: &Value) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Value {}
+
+impl PartialOrd for Value {
+    fn partial_cmp(&self, other: &Value) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Value {
+    fn cmp(&self, other: &Value) -> Ordering {
+        key(self).cmp(&key(other))
+    }
+}
+
+impl Hash for Value {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        key(self).hash(state)
+    }
+}
+
+/// A comparable, hashable projection of a `Value`, used so that `Value`
+/// itself can be used as a map key even though `f64` has neither `Ord` nor
+/// `Hash`. Floats are compared and hashed by their bit pattern, which is
+/// consistent (if not IEEE-754 equality) for `NaN`.
+fn key(value: &Value) -> (u8, Vec<u8>, u64, Option<Box<Value>>) {
+    match *value {
+        Value::Unit => (0, Vec::new(), 0, None),
+        Value::Bool(b) => (1, Vec::new(), b as u64, None),
+        // Flipping the sign bit maps the two's-complement range onto the
+        // unsigned range while preserving numeric order (unlike a raw bit
+        // cast, under which every negative value would sort after i64::MAX).
+        Value::I64(n) => (2, Vec::new(), (n as u64) ^ (1 << 63), None),
+        Value::U64(n) => (3, Vec::new(), n, None),
+        Value::F64(n) => (4, Vec::new(), n.to_bits(), None),
+        Value::Char(c) => (5, Vec::new(), c as u64, None),
+        Value::String(ref s) => (6, s.as_bytes().to_vec(), 0, None),
+        Value::Seq(ref seq) => {
+            let mut bytes = Vec::new();
+            for v in seq {
+                bytes.extend(format!("{:?}", v).into_bytes());
+            }
+            (7, bytes, seq.len() as u64, None)
+        }
+        Value::Map(ref map) => {
+            let mut bytes = Vec::new();
+            for (k, v) in map.iter() {
+                bytes.extend(format!("{:?}{:?}", k, v).into_bytes());
+            }
+            (8, bytes, map.len() as u64, None)
+        }
+        Value::EnumVariant(ref name, ref data) => {
+            (9, name.as_bytes().to_vec(), 0, data.clone())
+        }
+    }
+}
+
+impl ser::Serialize for Value {
+    fn serialize<S>(&self, serializer: &mut S) -> Result<(), S::Error>
+        where S: ser::Serializer,
+    {
+        match *self {
+            Value::Unit => serializer.visit_unit(),
+            Value::Bool(v) => serializer.visit_bool(v),
+            Value::I64(v) => serializer.visit_i64(v),
+            Value::U64(v) => serializer.visit_u64(v),
+            Value::F64(v) => serializer.visit_f64(v),
+            Value::Char(v) => serializer.visit_char(v),
+            Value::String(ref v) => serializer.visit_str(v),
+            Value::Seq(ref v) => v.serialize(serializer),
+            Value::Map(ref v) => v.serialize(serializer),
+            Value::EnumVariant(ref name, None) => {
+                // `Encoder::visit_enum_unit` writes its `name` argument, so
+                // the variant name goes there to come out as `¶Name¶`.
+                serializer.visit_enum_unit(name, name)
+            }
+            Value::EnumVariant(ref name, Some(ref data)) => {
+                serializer.visit_enum_seq("Value", name, OneValueVisitor(Some((**data).clone())))
+            }
+        }
+    }
+}
+
+/// Serializes a single boxed `Value` as the one-element seq that
+/// `visit_enum_seq` expects for a variant's payload.
+struct OneValueVisitor(Option<Value>);
+
+impl ser::SeqVisitor for OneValueVisitor {
+    fn visit<S>(&mut self, serializer: &mut S) -> Result<Option<()>, S::Error>
+        where S: ser::Serializer,
+    {
+        if let Some(value) = self.0.take() {
+            try!(serializer.visit_seq_elt(value));
+        }
+
+        Ok(None)
+    }
+
+    fn len(&self) -> Option<usize> {
+        Some(if self.0.is_some() { 1 } else { 0 })
+    }
+}
+
+struct ValueVisitor;
+
+impl de::Visitor for ValueVisitor {
+    type Value = Value;
+
+    fn visit_bool<E>(&mut self, v: bool) -> Result<Value, E> where E: de::Error {
+        Ok(Value::Bool(v))
+    }
+
+    fn visit_i64<E>(&mut self, v: i64) -> Result<Value, E> where E: de::Error {
+        Ok(Value::I64(v))
+    }
+
+    fn visit_u64<E>(&mut self, v: u64) -> Result<Value, E> where E: de::Error {
+        Ok(Value::U64(v))
+    }
+
+    fn visit_f64<E>(&mut self, v: f64) -> Result<Value, E> where E: de::Error {
+        Ok(Value::F64(v))
+    }
+
+    fn visit_char<E>(&mut self, v: char) -> Result<Value, E> where E: de::Error {
+        Ok(Value::Char(v))
+    }
+
+    fn visit_str<E>(&mut self, v: &str) -> Result<Value, E> where E: de::Error {
+        Ok(Value::String(v.to_owned()))
+    }
+
+    fn visit_string<E>(&mut self, v: String) -> Result<Value, E> where E: de::Error {
+        Ok(Value::String(v))
+    }
+
+    fn visit_unit<E>(&mut self) -> Result<Value, E> where E: de::Error {
+        Ok(Value::Unit)
+    }
+
+    fn visit_none<E>(&mut self) -> Result<Value, E> where E: de::Error {
+        Ok(Value::Unit)
+    }
+
+    fn visit_some<D>(&mut self, deserializer: &mut D) -> Result<Value, D::Error>
+        where D: de::Deserializer,
+    {
+        de::Deserialize::deserialize(deserializer)
+    }
+
+    fn visit_seq<V>(&mut self, mut visitor: V) -> Result<Value, V::Error>
+        where V: de::SeqVisitor,
+    {
+        let mut values = Vec::new();
+
+        while let Some(value) = try!(visitor.visit()) {
+            values.push(value);
+        }
+
+        try!(visitor.end());
+        Ok(Value::Seq(values))
+    }
+
+    fn visit_map<V>(&mut self, mut visitor: V) -> Result<Value, V::Error>
+        where V: de::MapVisitor,
+    {
+        let mut map = Map::new();
+
+        while let Some(key) = try!(visitor.visit_key()) {
+            let value = try!(visitor.visit_value());
+            map.insert(key, value);
+        }
+
+        try!(visitor.end());
+
+        // `{"Name":[data]}` is how `visit_enum_seq` writes a data-carrying
+        // enum variant (see `Value`'s `Serialize` impl); reconstruct it as
+        // `EnumVariant` instead of a one-entry `Map` so it round-trips. A
+        // plain RON map that happens to have this exact shape is genuinely
+        // indistinguishable from it on the wire.
+        if map.len() == 1 {
+            let (key, value) = map.into_iter().next().unwrap();
+            return Ok(match (key, value) {
+                (Value::String(name), Value::Seq(mut items)) if items.len() == 1 => {
+                    Value::EnumVariant(name, Some(Box::new(items.pop().unwrap())))
+                }
+                (key, value) => {
+                    let mut map = Map::new();
+                    map.insert(key, value);
+                    Value::Map(map)
+                }
+            });
+        }
+
+        Ok(Value::Map(map))
+    }
+}
+
+impl de::Deserialize for Value {
+    fn deserialize<D>(deserializer: &mut D) -> Result<Value, D::Error>
+        where D: de::Deserializer,
+    {
+        deserializer.visit(ValueVisitor)
+    }
+}
+
+/// Decodes a RON value from a `&str` into an untyped `Value`.
+pub fn from_str(s: &str) -> Result<Value, Error> {
+    ::decode::from_str(s)
+}
+
+/// Decodes a RON value from an `io::Read` into an untyped `Value`.
+pub fn from_reader<R: ::std::io::Read>(rdr: R) -> Result<Value, Error> {
+    ::decode::from_reader(rdr)
+}
+
+/// Encodes the `Value` back into a RON string.
+pub fn to_string(value: &Value) -> Result<String, Error> {
+    ::encode::to_string(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Value, Map, to_string, from_str};
+
+    fn round_trip(value: Value) {
+        let encoded = to_string(&value).unwrap();
+        let decoded = from_str(&encoded).unwrap();
+        assert_eq!(decoded, value);
+    }
+
+    #[test]
+    fn scalars_round_trip() {
+        round_trip(Value::Unit);
+        round_trip(Value::Bool(true));
+        round_trip(Value::I64(-42));
+        round_trip(Value::U64(42));
+        round_trip(Value::Char('x'));
+        round_trip(Value::String("hello".to_owned()));
+    }
+
+    #[test]
+    fn seq_round_trips() {
+        round_trip(Value::Seq(vec![Value::U64(1), Value::U64(2), Value::U64(3)]));
+    }
+
+    #[test]
+    fn map_round_trips() {
+        let mut map = Map::new();
+        map.insert(Value::String("a".to_owned()), Value::U64(1));
+        map.insert(Value::String("b".to_owned()), Value::U64(2));
+        round_trip(Value::Map(map));
+    }
+
+    #[test]
+    fn nested_seq_and_map_round_trip() {
+        let mut inner = Map::new();
+        inner.insert(Value::String("x".to_owned()), Value::Seq(vec![Value::Unit, Value::Bool(false)]));
+
+        let mut outer = Map::new();
+        outer.insert(Value::String("nested".to_owned()), Value::Map(inner));
+        round_trip(Value::Map(outer));
+    }
+
+    #[test]
+    fn data_carrying_enum_variant_round_trips() {
+        round_trip(Value::EnumVariant("Some".to_owned(), Some(Box::new(Value::U64(7)))));
+    }
+}